@@ -11,9 +11,22 @@ components_and_store! {
         pub name: CName (String),
         /// The age of an entity.
         pub age: CAge (u32),
+        /// An entity's priority when printed in sorted order.
+        pub priority: CPriority (u32),
+        /// An entity's honorific title, not every entity has one.
+        pub title: CTitle (String),
     }
 }
 
+// ============= Resources =============
+
+resources_and_store! {
+    /// Stores all the resources!
+    pub struct Resources {
+        /// The time elapsed since the last update, in seconds.
+        pub dt: RDeltaTime (f32),
+    }
+}
 
 // ============= Processes ================
 
@@ -28,9 +41,43 @@ process! {
 
 process! {
     pub mod double_age {
-        /// Doubles the age of an entity.
-        pub fn PDoubleAge::run(mut age[a]: &mut CAge,) {
+        /// Doubles the age of an entity, and spawns a child every time it
+        /// runs while the doubled age is above a threshold.
+        pub fn PDoubleAge::run(mut age[a]: &mut CAge, ref name[n]: &CName, cmd cmd,) {
             *age *= 2;
+            if *age > 1000 {
+                cmd.spawn(EPlayer::new_data(format!("{} Jr.", name), 0, 0, None));
+            }
+        }
+    }
+}
+
+process! {
+    pub mod age_by_dt {
+        /// Ages an entity by the simulation's delta time.
+        pub fn PAgeByDeltaTime::run(mut age[a]: &mut CAge, res dt[d]: &RDeltaTime,) {
+            *age += *dt as u32;
+        }
+    }
+}
+
+process! {
+    pub mod print_by_priority {
+        /// Prints entities lowest-priority-first, instead of storage order.
+        pub fn PPrintByPriority::run(ref name[n]: &CName, ref priority[p]: &CPriority,) order by priority {
+            println!("[{}] {}", priority, name);
+        }
+    }
+}
+
+process! {
+    pub mod print_title {
+        /// Prints an entity's name, with its title in front when it has one.
+        pub fn PPrintTitle::run(ref name[n]: &CName, opt title[t]: Option<&CTitle>,) {
+            match title {
+                Some(title) => println!("{} {}", title, name),
+                None => println!("{}", name),
+            }
         }
     }
 }
@@ -48,22 +95,47 @@ process_store! {
     pub struct Processes {
         print_info: PPrintInfo,
         double_age: PDoubleAge,
+        age_by_dt: PAgeByDeltaTime,
+        print_by_priority: PPrintByPriority,
+        print_title: PPrintTitle,
         pwln: PPrintWithLastName,
     }
 }
 
+// `PPrintWithLastName` takes an `ext` argument, so it can't be given a
+// uniform `fn(&mut Sim)` signature and is left out of the dispatcher.
+//
+// `PDoubleAge` takes a `cmd` argument, so it always runs in a batch by
+// itself (see `dispatcher!`'s docs), regardless of which other stage it's
+// labeled under here.
+dispatcher! {
+    /// Runs the entity-processing part of `update` in parallel, batched by
+    /// which processes actually conflict over component/resource access.
+    pub fn dispatch_update::run(Sim) {
+        stage_a: [ PDoubleAge, PAgeByDeltaTime ],
+        stage_b: [ PPrintInfo ],
+    }
+}
+
 // ============= Entities ================
 
 entity! {
     pub mod player {
         /// The avatar that the player controls in the game.
         pub struct EPlayer {
-            name: CName, 
+            name: CName,
             age: CAge,
+            priority: CPriority,
+        }
+        opt {
+            title: CTitle,
         }
         impl {
             PPrintInfo,
             PDoubleAge,
+            PAgeByDeltaTime,
+            PPrintByPriority,
+            PPrintTitle,
             PPrintWithLastName,
         }
     }
@@ -80,18 +152,24 @@ entity_store! {
 #[derive(Debug, Default)]
 pub struct Sim {
     components: Components,
+    resources: Resources,
     entities: Entities,
     processes: Processes,
 }
 
 impl Sim {
     pub fn new() -> Sim {
-        Sim::default()
+        let mut sim = Sim::default();
+        sim.resources.dt = 1.0;
+        sim
     }
-    
+
     pub fn update(&mut self) {
         PPrintInfo::run(self);
         PDoubleAge::run(self);
+        PAgeByDeltaTime::run(self);
+        PPrintByPriority::run(self);
+        PPrintTitle::run(self);
         PPrintWithLastName::run(self, "Erroinen");
     }
 }
@@ -104,33 +182,54 @@ contains_components! {
     Sim.components: Components
 }
 
+contains_resources! {
+    Sim.resources: Resources
+}
+
 contains_entities! {
     Sim.entities: Entities
 }
 
+contains_maintenance! {
+    Sim {
+        processes: Processes,
+        components: Components,
+    }
+}
+
 fn main() {
+    use complecs::traits::HasMaintenance;
+
     println!("Hello world!");
 
     let mut sim = Sim::new();
-    
-    let player = EPlayer::new_data(String::from("Jakob"), 22);
+
+    let player = EPlayer::new_data(String::from("Jakob"), 22, 1, Some(String::from("Sir")));
     player.add_to(&mut sim);
-    
-    let another = EPlayer::new_data(String::from("test"), 9001);
-    another.add_to(&mut sim);
-    
+
+    let another = EPlayer::new_data(String::from("test"), 9001, 0, None);
+    let another_handle = another.add_to(&mut sim);
+
     //println!("\n==== BEFORE WRITE ====\n");
     //println!("print_info: {:?}", sim.processes.print_info);
     //println!("players:    {:?}", sim.entities.players);
-    
+
     //sim.processes.print_info.write();
-    
+
     //println!("\n==== AFTER WRITE ====\n");
-    
+
     //println!("Sim: {:?}", sim);
     //println!("print_info: {:?}", sim.processes.print_info);
     //println!("players:    {:?}", sim.entities.players);
-    
+
     sim.update();
     sim.update();
+
+    // Despawn one of the players, then sync so froggy reclaims its slots.
+    player::despawn(&mut sim, another_handle);
+    sim.maintain();
+
+    sim.update();
+
+    dispatch_update(&mut sim);
 }
@@ -51,17 +51,84 @@ macro_rules! components_and_store {
     }
 }
 
+/// Declares a resource with the given name and type.
+///
+/// Unlike a component, a resource is global state shared by every
+/// process that asks for it, rather than something stored per entity.
+#[macro_export]
+macro_rules! resource {
+    (
+        $( #[$meta:meta] )*
+        pub $name:ident ( $type:ty );
+    ) => {
+        $( #[$meta] )*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl complecs::traits::ResId for $name {
+            type Type = $type;
+        }
+    }
+}
+
+/// Declares multiple resources with the given names and types, and
+/// a struct for storing them.
+#[macro_export]
+macro_rules! resources_and_store {
+    // No trailing comma.
+    (
+        $( #[ $store_meta:meta ] )*
+        pub struct $store:ident {
+            $(
+                $( #[ $res_meta:meta ] )*
+                pub $member:ident : $name:ident ( $type:ty )
+            ),*
+            $(,)*
+        }
+    ) => {
+
+        $(
+            resource! {
+                $( #[ $res_meta ] )*
+                pub $name ( $type );
+            }
+        )*
+
+        $( #[ $store_meta ] )*
+        resource_store! {
+            pub struct $store {
+                $(
+                    $member : $name,
+                )*
+            }
+        }
+    }
+}
+
 /// Declares a component with the members inside a created module.
 /// The process takes a set of mutable and immutable components as arguments,
 /// as declared with the `mut` and `ref` arguments.
 ///
-/// The first identifier after the ref/mut is a symbol used in the generated code,
-/// but with no real influence on the function body.
-/// 
+/// Each argument is written as `name[gensym]`: `name` is the binding the
+/// body sees, and `[gensym]` is a symbol used internally in the generated
+/// code, with no influence on the function body.
+///
 /// The body of the run function is executed in a context, in which the
 /// components have been loaded and converted to their associated types.
-/// The run function can also have extra `ext` arguments declared, that
-/// are just passed directly to the scope.
+/// The process can also declare `opt name[n]: Option<&C>` arguments for
+/// components that not every entity using it is guaranteed to carry
+/// (see `entity!`'s `opt { }` block) — the body sees `Option<&C::Type>`
+/// instead of `&C::Type` for these — `res`/`res mut` arguments to
+/// read/write a resource shared by the whole simulation instead of a
+/// per-entity component, and extra `ext` arguments, that are just passed
+/// directly to the scope. A single optional `cmd` argument names a `Commands`
+/// buffer that the body can use to `spawn`/`despawn` entities; those
+/// calls are only applied once the loop has finished and every
+/// component guard has been dropped, so they can't deadlock or alias
+/// the storages the process is already iterating. An optional trailing
+/// `order by <name>` clause, naming one of the `ref`/`mut` arguments
+/// declared above, makes the process visit its entities stably sorted
+/// by that argument's value instead of storage order.
 #[macro_export]
 macro_rules! process {
     (
@@ -70,14 +137,39 @@ macro_rules! process {
             $( #[$run_meta:meta] )*
             pub fn $proc_id:ident::run(
                 // Mutable components, always first.
-                $( mut $mut_gensym:ident $mut_arg:ident : &mut $mut_comp:ident, )*
-                
+                $( mut $mut_arg:ident [ $mut_gensym:ident ] : &mut $mut_comp:ident, )*
+
                 // Immutable components.
-                $( ref $gensym:ident $arg:ident : & $comp:ident, )*
-                
+                $( ref $arg:ident [ $gensym:ident ] : & $comp:ident, )*
+
+                // Components that not every entity using this process is
+                // guaranteed to carry. `Some` if this entity's `CompRefs`
+                // was built with the component (see `entity!`'s `opt { }`
+                // block), `None` otherwise.
+                $( opt $opt_arg:ident [ $opt_gensym:ident ] : Option<& $opt_comp:ident>, )*
+
+                // Mutable resources (shared global state), always before
+                // immutable resources.
+                $( res mut $res_mut_arg:ident [ $res_mut_gensym:ident ] : &mut $res_mut:ident, )*
+
+                // Immutable resources.
+                $( res $res_arg:ident [ $res_gensym:ident ] : & $res:ident, )*
+
                 // External arguments (relevant here?)
                 $( ext $ext_arg:ident : $ext_ty:ty, )*
-            ) $body:block
+
+                // An optional deferred command buffer, see `Commands`.
+                $( cmd $cmd_arg:ident, )?
+            )
+            // An optional sort key, naming one of the `ref`/`mut` arguments
+            // declared above by its binding name. Entities are then visited
+            // in ascending order of that argument's value instead of
+            // storage order. Relies on plain Rust name resolution (rather
+            // than macro-level matching) to connect `$order_key` back to
+            // the matching `ref`/`mut` argument, so a typo here just fails
+            // to compile as an unresolved identifier.
+            $( order by $order_key:ident )?
+            $body:block
         }
     ) => {
         $( #[$meta] )*
@@ -92,8 +184,9 @@ macro_rules! process {
             /// Indices to arguments of this process.
             pub type ArgRefs = (
                 $( froggy::StorageRc<<super::$mut_comp as traits::CompId>::Type>, )*
-                $( froggy::StorageRc<<super::$comp as traits::CompId>::Type>, )* 
-            );    
+                $( froggy::StorageRc<<super::$comp as traits::CompId>::Type>, )*
+                $( Option<froggy::StorageRc<<super::$opt_comp as traits::CompId>::Type>>, )*
+            );
         }
         
         /// An ECS process.
@@ -106,20 +199,56 @@ macro_rules! process {
         
         // Make sure that entities can only be added to this process 
         // inside the right storage types.
-        unsafe impl<S> complecs::traits::AddEntityToProcess<S> for $proc_id 
+        unsafe impl<S> complecs::traits::AddEntityToProcess<S> for $proc_id
           where S: complecs::traits::HasProcStore<$proc_id>
               $( + complecs::traits::HasCompStore<$mut_comp> )*
               $( + complecs::traits::HasCompStore<$comp> )*
+              $( + complecs::traits::HasCompStore<$opt_comp> )*
         {}
-        
-        // Ensure that arguments are only accessed once by this process.
+
+        // Ensure that arguments are only accessed once by this process,
+        // and record whether each is read or written, so a `dispatcher!`
+        // can tell which processes may safely run in parallel.
         $(
-            impl complecs::traits::HasArg<$mut_comp> for $proc_id {}
+            impl complecs::traits::HasMutArg<$mut_comp> for $proc_id {}
         )*
         $(
-            impl complecs::traits::HasArg<$comp> for $proc_id {}
+            impl complecs::traits::HasRefArg<$comp> for $proc_id {}
         )*
-        
+        $(
+            impl complecs::traits::HasRefArg<$opt_comp> for $proc_id {}
+        )*
+
+        impl complecs::traits::ProcArgIds for $proc_id {
+            fn write_ids() -> Vec<std::any::TypeId> {
+                vec![
+                    $( std::any::TypeId::of::<$mut_comp>(), )*
+                    $( std::any::TypeId::of::<$res_mut>(), )*
+                ]
+            }
+
+            fn read_ids() -> Vec<std::any::TypeId> {
+                vec![
+                    $( std::any::TypeId::of::<$comp>(), )*
+                    $( std::any::TypeId::of::<$opt_comp>(), )*
+                    $( std::any::TypeId::of::<$res>(), )*
+                ]
+            }
+        }
+
+        impl $proc_id {
+            /// Whether this process takes a `cmd` argument.
+            ///
+            /// A queued spawn/despawn registers/drops `ArgRefs` in the
+            /// `Storage` of *every* process that the spawned entity type
+            /// implements, not just this one — a write `write_ids`/
+            /// `read_ids` can't see, since it isn't a component or
+            /// resource access. `dispatcher!` reads this to keep a `cmd`
+            /// process from ever sharing a batch with another process,
+            /// regardless of what `ProcArgIds` reports.
+            pub const HAS_CMD: bool = false $( || { let _ = stringify!($cmd_arg); true } )?;
+        }
+
         // Add the run function, and ensure that this too can only
         // be run on a simulation type with the right components.
         impl $proc_id {
@@ -129,48 +258,152 @@ macro_rules! process {
                     S: complecs::traits::HasProcStore<$proc_id>
                   $( + complecs::traits::HasCompStore<$mut_comp> )*
                   $( + complecs::traits::HasCompStore<$comp> )*
+                  $( + complecs::traits::HasCompStore<$opt_comp> )*
+                  $( + complecs::traits::HasResource<$res_mut> )*
+                  $( + complecs::traits::HasResource<$res> )*
             {
-                $(  
-                    let mut $mut_arg = unsafe {
-                        &mut * <S as complecs::traits::HasCompStore<$mut_comp>>::get_mut_components(sim)
-                    }.write();
-                )*
-                $(
-                    let $arg = unsafe {
-                        & * <S as complecs::traits::HasCompStore<$comp>>::get_components(sim)
-                    }.read();
-                )*
-                
-                for &( $( ref $mut_gensym, )* $( ref $gensym, )* )
-                in &<S as complecs::traits::HasProcStore<$proc_id>>::process_members(sim).read() {
+                $( let mut $cmd_arg = complecs::commands::Commands::<S>::new(); )?
+
+                {
+                    $(
+                        let mut $mut_arg = unsafe {
+                            &mut * <S as complecs::traits::HasCompStore<$mut_comp>>::get_mut_components(sim)
+                        }.write();
+                    )*
+                    $(
+                        let $arg = unsafe {
+                            & * <S as complecs::traits::HasCompStore<$comp>>::get_components(sim)
+                        }.read();
+                    )*
                     $(
-                        let $mut_arg = $mut_arg.get_mut($mut_gensym);
+                        let $opt_arg = unsafe {
+                            & * <S as complecs::traits::HasCompStore<$opt_comp>>::get_components(sim)
+                        }.read();
                     )*
                     $(
-                        let $arg = $arg.get($gensym);
+                        let $res_mut_arg = unsafe {
+                            &mut * <S as complecs::traits::HasResource<$res_mut>>::get_mut_resource(sim)
+                        };
                     )*
-                    $body
+                    $(
+                        let $res_arg = unsafe {
+                            & * <S as complecs::traits::HasResource<$res>>::get_resource(sim)
+                        };
+                    )*
+
+                    // Snapshotted into a `Vec` so an `order by` clause can
+                    // sort it without disturbing froggy's own storage order.
+                    let mut __members: Vec<_> =
+                        <S as complecs::traits::HasProcStore<$proc_id>>::process_members(sim)
+                            .read().iter().cloned().collect();
+
+                    // Delegates to a separate macro rather than gating this
+                    // block on `$( order by $order_key:ident )?` directly:
+                    // that would nest the independently-sized `mut`/`ref`/
+                    // `opt` argument lists inside a single `?`-repetition,
+                    // which only expands when all of their counts happen
+                    // to match. `__process_order_by!` re-matches on
+                    // whether `$order_key` is present as its own, separate
+                    // macro invocation, sidestepping the clash entirely.
+                    __process_order_by!(
+                        __members,
+                        ( $( $order_key )? ),
+                        ( $( $mut_gensym $mut_arg ),* ),
+                        ( $( $gensym $arg ),* ),
+                        ( $( $opt_gensym $opt_arg ),* ),
+                    );
+
+                    for &( $( ref $mut_gensym, )* $( ref $gensym, )* $( ref $opt_gensym, )* ) in &__members {
+                        $(
+                            let $mut_arg = $mut_arg.get_mut($mut_gensym);
+                        )*
+                        $(
+                            let $arg = $arg.get($gensym);
+                        )*
+                        $(
+                            let $opt_arg = $opt_gensym.as_ref().map(|h| $opt_arg.get(h));
+                        )*
+                        $body
+                    }
+                    // All component guards above are dropped at the end of
+                    // this block, before the command buffer (which may
+                    // itself need to acquire those guards again) is applied.
                 }
+
+                $( $cmd_arg.apply(sim); )?
             }
         }
         
         // NOTE: The debug clause allows the concatenation of bounds.
         // Ensure that generated entities can be added to this process.
+        //
+        // `opt` arguments are bounded by `HasOptComp` rather than
+        // `HasComp`, since every entity type using this process declares
+        // one (via `entity!`'s `opt { }` block) regardless of whether any
+        // given instance actually carries the component.
         impl<E> complecs::traits::ProcArgsFrom<E> for $proc_id
-          where E: self::$mod::Debug 
+          where E: self::$mod::Debug
                    $( + complecs::traits::HasComp<$mut_comp> )*
                    $( + complecs::traits::HasComp<$comp> )*
+                   $( + complecs::traits::HasOptComp<$opt_comp> )*
         {
             fn from_entity(e: &E) -> self::$mod::ArgRefs {
                 (
-                    $(<E as complecs::traits::HasComp<$mut_comp>>::get(e).clone() , )* 
+                    $(<E as complecs::traits::HasComp<$mut_comp>>::get(e).clone() , )*
                     $(<E as complecs::traits::HasComp<$comp>>::get(e).clone() , )*
+                    $(<E as complecs::traits::HasOptComp<$opt_comp>>::get(e) , )*
                 )
             }
         }
     }
 }
 
+/// Sorts a process's snapshotted `__members` by its `order by` key, if one
+/// was given. Used only by `process!`.
+///
+/// Split out of `process!` itself so that whether `$order_key` is present
+/// can be matched as two separate rules here, rather than as a single
+/// `$( order by $order_key:ident )?` wrapped around the `mut`/`ref`/`opt`
+/// argument lists — those are independently-sized repetitions, and
+/// nesting them inside one `?`-repetition only expands when their counts
+/// happen to agree.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __process_order_by {
+    (
+        $members:ident,
+        ( $order_key:ident ),
+        ( $( $mut_gensym:ident $mut_arg:ident ),* ),
+        ( $( $gensym:ident $arg:ident ),* ),
+        ( $( $opt_gensym:ident $opt_arg:ident ),* ),
+    ) => {
+        $members.sort_by(|a, b| {
+            let a_key = {
+                let &( $( ref $mut_gensym, )* $( ref $gensym, )* $( ref $opt_gensym, )* ) = a;
+                $( let $mut_arg = $mut_arg.get_mut($mut_gensym); )*
+                $( let $arg = $arg.get($gensym); )*
+                $( let $opt_arg = $opt_gensym.as_ref().map(|h| $opt_arg.get(h)); )*
+                $order_key.clone()
+            };
+            let b_key = {
+                let &( $( ref $mut_gensym, )* $( ref $gensym, )* $( ref $opt_gensym, )* ) = b;
+                $( let $mut_arg = $mut_arg.get_mut($mut_gensym); )*
+                $( let $arg = $arg.get($gensym); )*
+                $( let $opt_arg = $opt_gensym.as_ref().map(|h| $opt_arg.get(h)); )*
+                $order_key.clone()
+            };
+            a_key.partial_cmp(&b_key).unwrap_or(std::cmp::Ordering::Equal)
+        });
+    };
+    (
+        $members:ident,
+        (),
+        ( $( $mut_gensym:ident $mut_arg:ident ),* ),
+        ( $( $gensym:ident $arg:ident ),* ),
+        ( $( $opt_gensym:ident $opt_arg:ident ),* ),
+    ) => {};
+}
+
 /// Declares a new entity, with its members contained in the module with the given name.
 ///
 /// # Generation example
@@ -209,7 +442,21 @@ macro_rules! entity {
                 ),*
                 $(,)*
             }
-            
+
+            // Components this entity type declares as present-or-absent
+            // per instance, rather than always present. A process can
+            // read one of these via an `opt name[n]: Option<&C>` argument
+            // (see `process!`) and gets `None` for instances that weren't
+            // given one.
+            $(
+                opt {
+                    $(
+                        $opt_name:ident : $opt_id:ident
+                    ),*
+                    $(,)*
+                }
+            )?
+
             impl {
                 $( $proc_id:ident ),*
                 $(,)*
@@ -224,7 +471,23 @@ macro_rules! entity {
         
             /// The data that should be stored about this entity to keep it alive.
             pub type ProcData = ( $( froggy::StorageRc<<super::$proc_id as traits::ProcId>::ArgRefs> ),* ,);
-        
+
+            /// An opaque reference to a spawned entity, returned by `add_to`.
+            ///
+            /// Pass this to `despawn` to remove the entity again. `despawn`
+            /// uses `Vec::swap_remove`, so removing an entity moves
+            /// whichever entity was last in the store into the freed slot;
+            /// every handle keeps pointing at the same entity it always
+            /// did, *except* the handle for that formerly-last entity,
+            /// which now points one past the end of the store (and at the
+            /// next entity of this type to be spawned, once there is one),
+            /// while the moved entity is reached through the despawned
+            /// entity's handle instead. Don't hold onto a handle across a
+            /// `despawn` of the same entity type unless you know it isn't
+            /// the last one in the store.
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub struct EntityHandle(usize);
+
             $( #[ $entity_meta ] )*
             #[derive(Debug, Clone, Copy)]
             pub struct $entity_id;
@@ -243,13 +506,17 @@ macro_rules! entity {
             
             impl $entity_id {
                 /// Creates the source data for an entity of this type.
-                pub fn new_data( $( $comp_name : <super::$comp_id as traits::CompId>::Type ),* ) -> Data {
+                pub fn new_data(
+                    $( $comp_name : <super::$comp_id as traits::CompId>::Type, )*
+                    $( $( $opt_name : Option<<super::$opt_id as traits::CompId>::Type>, )* )?
+                ) -> Data {
                     Data::new(
-                        $( $comp_name ),*
+                        $( $comp_name, )*
+                        $( $( $opt_name, )* )?
                     )
                 }
             }
-        
+
             // Create the data used to add the item.
             /// Data used to add this entity to a simulation.
             #[derive(Debug)]
@@ -258,22 +525,33 @@ macro_rules! entity {
                     /// A component.
                     pub $comp_name : <super::$comp_id as traits::CompId>::Type,
                 )*
+                $( $(
+                    /// A component this instance may or may not carry.
+                    pub $opt_name : Option<<super::$opt_id as traits::CompId>::Type>,
+                )* )?
             }
-        
+
             impl Data {
                 /// Creates a new set of entity data.
-                pub fn new( $( $comp_name : <super::$comp_id as traits::CompId>::Type ),* ) -> Data {
+                pub fn new(
+                    $( $comp_name : <super::$comp_id as traits::CompId>::Type, )*
+                    $( $( $opt_name : Option<<super::$opt_id as traits::CompId>::Type>, )* )?
+                ) -> Data {
                     Data {
-                        $( $comp_name ),*
+                        $( $comp_name, )*
+                        $( $( $opt_name, )* )?
                     }
                 }
-                
-                
-                pub fn add_to<S>(self, sim: &mut S) 
+
+
+                pub fn add_to<S>(self, sim: &mut S) -> EntityHandle
                   where S: traits::HasEntityStore<self::$entity_id>
                     $(
                         + traits::HasCompStore<super::$comp_id>
                     )*
+                    $( $(
+                        + traits::HasCompStore<super::$opt_id>
+                    )* )?
                     $(
                         + traits::HasProcStore<super::$proc_id>
                     )*
@@ -286,19 +564,80 @@ macro_rules! entity {
                             &mut * <S as traits::HasCompStore<super::$comp_id>>::get_mut_components(sim)
                         }.write().insert(self.$comp_name);
                     )*
+                    $( $(
+                        let $opt_name = self.$opt_name.map(|value| unsafe {
+                            &mut * <S as traits::HasCompStore<super::$opt_id>>::get_mut_components(sim)
+                        }.write().insert(value));
+                    )* )?
                     let components = CompRefs {
                         $(
-                            $comp_name
-                        ),*
+                            $comp_name,
+                        )*
+                        $( $(
+                            $opt_name,
+                        )* )?
                     };
                     let entity = ( $(
                         <super::$proc_id as traits::AddEntityToProcess<S>>::add_entity(sim, &components.clone())
                     ),* ,);
-                    <S as traits::HasEntityStore<self::$entity_id>>::get_mut_entities(sim).push(entity);
+                    let entities = <S as traits::HasEntityStore<self::$entity_id>>::get_mut_entities(sim);
+                    let handle = EntityHandle(entities.len());
+                    entities.push(entity);
+                    handle
                 }
-                
+
             }
-        
+
+            /// Removes the entity behind `handle` from the simulation.
+            ///
+            /// This drops the entity's `ProcData` tuple out of the entity
+            /// store, which in turn drops its `ArgRefs` out of every process
+            /// `Storage` it was registered with, releasing froggy's hold on
+            /// the underlying components. froggy only reclaims those slots
+            /// at the next sync point, so follow this up with a call to
+            /// `Sim::maintain` (see `traits::HasMaintenance`).
+            ///
+            /// Uses `Vec::swap_remove` rather than `Vec::remove`, so this
+            /// is O(1) and only invalidates one other handle (see
+            /// `EntityHandle`'s docs) instead of shifting every entity
+            /// after `handle` down by one and silently invalidating all
+            /// of their handles at once.
+            pub fn despawn<S>(sim: &mut S, handle: EntityHandle)
+              where S: traits::HasEntityStore<self::$entity_id>
+            {
+                <S as traits::HasEntityStore<self::$entity_id>>::get_mut_entities(sim).swap_remove(handle.0);
+            }
+
+            // Let a `Commands` buffer defer spawning/despawning this entity
+            // type until after a process's iteration has finished.
+            impl<S> traits::DeferredSpawn<S> for Data
+              where S: traits::HasEntityStore<self::$entity_id>
+                $(
+                    + traits::HasCompStore<super::$comp_id>
+                )*
+                $( $(
+                    + traits::HasCompStore<super::$opt_id>
+                )* )?
+                $(
+                    + traits::HasProcStore<super::$proc_id>
+                )*
+                $(
+                    , super::$proc_id : traits::AddEntityToProcess<S>
+                )*
+            {
+                fn apply_spawn(self, sim: &mut S) {
+                    self.add_to(sim);
+                }
+            }
+
+            impl<S> traits::DeferredDespawn<S> for EntityHandle
+              where S: traits::HasEntityStore<self::$entity_id>
+            {
+                fn apply_despawn(self, sim: &mut S) {
+                    self::despawn(sim, self);
+                }
+            }
+
             /// A struct holding references to the components of this entity inside
             /// a store. 
             /// 
@@ -310,8 +649,12 @@ macro_rules! entity {
                     /// A component.
                     pub $comp_name : froggy::StorageRc<<super::$comp_id as traits::CompId>::Type>,
                 )*
+                $( $(
+                    /// A component this instance may or may not carry.
+                    pub $opt_name : Option<froggy::StorageRc<<super::$opt_id as traits::CompId>::Type>>,
+                )* )?
             }
-        
+
             $(
                 impl traits::HasComp<super::$comp_id> for self::CompRefs {
                     fn get(&self) -> &froggy::StorageRc<<super::$comp_id as traits::CompId>::Type> {
@@ -319,6 +662,13 @@ macro_rules! entity {
                     }
                 }
             )*
+            $( $(
+                impl traits::HasOptComp<super::$opt_id> for self::CompRefs {
+                    fn get(&self) -> Option<froggy::StorageRc<<super::$opt_id as traits::CompId>::Type>> {
+                        self.$opt_name.clone()
+                    }
+                }
+            )* )?
         }
         
         // Export the identifier.
@@ -354,12 +704,61 @@ macro_rules! component_store {
                 fn get_mut_components(&mut self) -> *mut complecs::froggy::Storage<<$component as complecs::traits::CompId>::Type> {
                     &mut self.$member
                 }
-    
+
                 fn get_components(&self) -> *const complecs::froggy::Storage<<$component as complecs::traits::CompId>::Type> {
                     &self.$member
                 }
             }
         )*
+
+        impl complecs::traits::HasMaintenance for $storage {
+            fn maintain(&mut self) {
+                $(
+                    self.$member.sync_pending();
+                )*
+            }
+        }
+    }
+}
+
+/// Declares a storage type for the identified resources.
+///
+/// Unlike components, resources are not kept in a froggy `Storage` since
+/// there is only ever one instance of each: the storage just holds the
+/// bare value directly.
+#[macro_export]
+macro_rules! resource_store {
+    // No trailing comma
+    (
+        $( #[$storage_meta:meta] )*
+        pub struct $storage:ident {
+            $(
+                $member:ident : $resource:ty
+            ),*
+            $(,)*
+        }
+    ) => {
+        /// A storage type for resources in an ECS system.
+        $( #[ $storage_meta ] )*
+        #[derive(Debug, Default)]
+        pub struct $storage {
+            $(
+                /// A resource [macro-generated].
+                pub $member : <$resource as complecs::traits::ResId>::Type
+            ),*
+        }
+
+        $(
+            unsafe impl complecs::traits::HasResource<$resource> for $storage {
+                fn get_mut_resource(&mut self) -> *mut <$resource as complecs::traits::ResId>::Type {
+                    &mut self.$member
+                }
+
+                fn get_resource(&self) -> *const <$resource as complecs::traits::ResId>::Type {
+                    &self.$member
+                }
+            }
+        )*
     }
 }
 
@@ -391,12 +790,116 @@ macro_rules! process_store {
                 fn process_members_mut(&mut self) -> &mut complecs::froggy::Storage<<$proc_id as complecs::traits::ProcId>::ArgRefs> {
                     &mut self.$member
                 }
-    
+
                 fn process_members(&self) -> &complecs::froggy::Storage<<$proc_id as complecs::traits::ProcId>::ArgRefs> {
                     &self.$member
                 }
-            } 
+            }
         )*
+
+        impl complecs::traits::HasMaintenance for $storage {
+            fn maintain(&mut self) {
+                $(
+                    self.$member.sync_pending();
+                )*
+            }
+        }
+    }
+}
+
+/// Declares a function that runs a set of processes, batched so that
+/// non-conflicting processes run concurrently with `rayon`.
+///
+/// Stage labels (`stage_a`, `stage_b`, ...) only order the processes as
+/// they're declared; the actual batches are computed below from each
+/// process's `ProcArgIds`, not from the labels, so a mis-labeled process
+/// still runs safely (just possibly in a later batch than expected).
+/// Two processes may share a batch iff the write-set of each is disjoint
+/// from the union of the other's read-set and write-set; batches are
+/// assigned greedily, in declared order. This requires every process
+/// passed in to take no `ext` arguments, since all of them must share
+/// the uniform `fn(&mut S)` signature to be called through one table.
+/// A process with a `cmd` argument is always kept in a batch by itself:
+/// its queued spawns/despawns can structurally mutate the `Storage` of
+/// any other process sharing an entity type with it, which isn't
+/// visible in `ProcArgIds`'s component-level read/write sets.
+#[macro_export]
+macro_rules! dispatcher {
+    (
+        $( #[$meta:meta] )*
+        pub fn $dispatch_id:ident::run( $sim_ty:ty ) {
+            $(
+                $stage:ident : [ $( $proc_id:ident ),* $(,)* ]
+            ),*
+            $(,)*
+        }
+    ) => {
+        $( #[$meta] )*
+        pub fn $dispatch_id(sim: &mut $sim_ty)
+          where $sim_ty: Send + Sync
+        {
+            // A process to run, together with the `TypeId`s of the
+            // components/resources it writes to and reads from, and whether
+            // it takes a `cmd` argument.
+            let entries: Vec<(Vec<std::any::TypeId>, Vec<std::any::TypeId>, bool, fn(&mut $sim_ty))> = vec![
+                $( $(
+                    (
+                        <$proc_id as complecs::traits::ProcArgIds>::write_ids(),
+                        <$proc_id as complecs::traits::ProcArgIds>::read_ids(),
+                        <$proc_id>::HAS_CMD,
+                        <$proc_id>::run::<$sim_ty> as fn(&mut $sim_ty),
+                    ),
+                )* )*
+            ];
+
+            // Greedily assign each process to the earliest batch it
+            // doesn't conflict with, in declared order. A process with
+            // `HAS_CMD` set always conflicts, both ways: it can't join an
+            // existing batch, and no later process can join the batch it
+            // starts, since either one might be racing a spawn/despawn
+            // that structurally touches the other's `Storage`.
+            let mut batches: Vec<Vec<fn(&mut $sim_ty)>> = Vec::new();
+            let mut batch_writes: Vec<Vec<std::any::TypeId>> = Vec::new();
+            let mut batch_reads: Vec<Vec<std::any::TypeId>> = Vec::new();
+            let mut batch_has_cmd: Vec<bool> = Vec::new();
+            'entries: for (writes, reads, has_cmd, run) in entries {
+                for i in 0..batches.len() {
+                    let conflicts = has_cmd || batch_has_cmd[i]
+                        || writes.iter().any(|t| batch_writes[i].contains(t) || batch_reads[i].contains(t))
+                        || reads.iter().any(|t| batch_writes[i].contains(t));
+                    if !conflicts {
+                        batch_writes[i].extend(writes);
+                        batch_reads[i].extend(reads);
+                        batches[i].push(run);
+                        continue 'entries;
+                    }
+                }
+                batches.push(vec![run]);
+                batch_writes.push(writes);
+                batch_reads.push(reads);
+                batch_has_cmd.push(has_cmd);
+            }
+
+            // A raw pointer wrapped in a type marked `Send` and `Sync`,
+            // where `&mut T` couldn't be shared, so each process in a batch
+            // can get its own alias into `sim`. This is sound only because
+            // the batches above are conflict-free.
+            struct SyncPtr<T>(*mut T);
+            unsafe impl<T> Send for SyncPtr<T> {}
+            unsafe impl<T> Sync for SyncPtr<T> {}
+            let sim_ptr = SyncPtr(sim as *mut $sim_ty);
+
+            for batch in &batches {
+                complecs::rayon::scope(|s| {
+                    for &run in batch {
+                        let sim_ptr = &sim_ptr;
+                        s.spawn(move |_| {
+                            run(unsafe { &mut *sim_ptr.0 });
+                        });
+                    }
+                });
+            }
+        }
     }
 }
 
@@ -452,6 +955,25 @@ macro_rules! contains_components {
     }
 }
 
+/// Describes that all resources stored by the member of the type is also
+/// stored by the type.
+#[macro_export]
+macro_rules! contains_resources {
+    (
+        $type:ident.$member:ident: $res_type:ty
+    ) => {
+        unsafe impl<R> complecs::traits::HasResource<R> for $type where R: complecs::traits::ResId, $res_type: complecs::traits::HasResource<R> {
+            fn get_mut_resource(&mut self) -> *mut <R as complecs::traits::ResId>::Type {
+                self.$member.get_mut_resource()
+            }
+
+            fn get_resource(&self) -> *const <R as complecs::traits::ResId>::Type {
+                self.$member.get_resource()
+            }
+        }
+    }
+}
+
 /// Describes that all processes stored by the member of the type is also
 /// stored by the type.
 #[macro_export]
@@ -472,6 +994,38 @@ macro_rules! contains_processes {
     }
 }
 
+/// Wires up a `Sim`-level `maintain()`/sync step, by calling `maintain` on
+/// every listed component/process store member in turn. This should be
+/// called periodically (e.g. once per `update`) so that froggy reclaims
+/// the storage slots freed by `despawn`.
+///
+/// List the process store before the component store: `despawn` only
+/// drops an entity's `ArgRefs` out of the process store, and it's
+/// *dropping* those `ArgRefs` (which happens when the process store's
+/// `sync_pending` runs) that queues the underlying component `StorageRc`s
+/// as pending in the component store. Maintaining components first
+/// leaves that cycle's freed component slots un-reclaimed until the
+/// next `maintain()` call.
+#[macro_export]
+macro_rules! contains_maintenance {
+    (
+        $type:ident {
+            $(
+                $member:ident : $store:ty
+            ),*
+            $(,)*
+        }
+    ) => {
+        impl complecs::traits::HasMaintenance for $type {
+            fn maintain(&mut self) {
+                $(
+                    complecs::traits::HasMaintenance::maintain(&mut self.$member);
+                )*
+            }
+        }
+    }
+}
+
 /// Describes that all entities stored by the member of the type is also
 /// stored by the type.
 #[macro_export]